@@ -0,0 +1,30 @@
+use std::{env, path::PathBuf};
+
+/// Builds the C helpers that are embedded in maxitest: the allocation failure injector preloaded
+/// in minishell, and the reaper that runs the shells in the bwrap sandbox
+fn main() {
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let compiler = cc::Build::new().opt_level(2).get_compiler();
+    let helpers: [(&str, &str, &[&str]); 2] = [
+        (
+            "src/run/inject.c",
+            "libmaxitest_inject.so",
+            &["-shared", "-fPIC"],
+        ),
+        // static so that it runs in the sandbox whatever libraries are there
+        ("src/run/reaper.c", "maxitest-reaper", &["-static"]),
+    ];
+    for (source, output, flags) in helpers {
+        println!("cargo::rerun-if-changed={source}");
+        let status = compiler
+            .to_command()
+            .args(flags)
+            .args(["-Wall", "-Wextra", "-o"])
+            .arg(out_dir.join(output))
+            .arg(source)
+            .arg("-ldl")
+            .status()
+            .expect("failed to run the C compiler");
+        assert!(status.success(), "failed to build {source}");
+    }
+}
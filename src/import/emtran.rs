@@ -47,20 +47,20 @@ fn parse(reader: impl io::Read, header_size: usize) -> Result<Tests, ParseTestEr
             },
         };
         let commands = record.get(1).unwrap_or("");
-        if [
-            "Ctlr-",
-            "env -i",
-            "[touche du haut]",
-            "!!! Contenu du fichier",
-        ]
-        .iter()
-        .any(|str| commands.contains(str))
+        if ["Ctlr-", "env -i", "[touche du haut]"]
+            .iter()
+            .any(|str| commands.contains(str))
         {
             continue;
         }
         let mut expected = record.get(7).unwrap_or_default().lines();
         let mut lines = Vec::<String>::new();
-        for line in commands.lines() {
+        // file contents are checked by comparing the test directories, drop the annotations
+        for line in commands
+            .lines()
+            .filter_map(|line| line.split("!!! Contenu du fichier").next())
+            .filter(|line| !line.trim().is_empty())
+        {
             let line = match line.strip_prefix("$> ") {
                 Some(line) => line.to_string(),
                 None => match lines.last_mut() {
@@ -88,7 +88,11 @@ fn parse(reader: impl io::Read, header_size: usize) -> Result<Tests, ParseTestEr
             .replace("sleep 3", "sleep 0")
             .replace("vietdu91", "maxitester");
         let id = out.len();
-        out.push(Test { id, commands });
+        out.push(Test {
+            id,
+            commands,
+            ..Default::default()
+        });
     }
     Ok((mandatory, bonus, more))
 }
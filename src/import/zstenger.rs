@@ -82,6 +82,7 @@ fn parse(mut reader: impl io::Read) -> Result<Vec<Test>, ParseTestError> {
                 tests.push(Test {
                     id,
                     commands: commands.join("\n"),
+                    ..Default::default()
                 });
                 commands = vec![];
             }
@@ -7,8 +7,10 @@ mod watch;
 use clap::{Args, Parser, Subcommand};
 use colored::Colorize;
 use import::{import_emtran, import_zstenger, ImportError};
+use regex::Regex;
 use run::{parse_tests, run_tests, RunError, TestResult};
 use std::{
+    collections::HashMap,
     fmt::Debug,
     path::PathBuf,
 };
@@ -32,6 +34,11 @@ use watch::WatchError;
 /// For any xxx.csv file, a xxx.ignore file can contain a list of test ids to ignore. One id per
 /// line, use # to add comments.
 ///
+/// A test line starting with "@@ " is a step instead of typed input: "@@ ctrl-c", "@@ ctrl-\",
+/// "@@ ctrl-d" or "@@ signal TERM", sent once the shell is ready, or after a delay with
+/// "@@ ctrl-c after 500ms", or once some text is printed with "@@ ctrl-c when $ ". Keys are sent
+/// to the terminal with --pty, and turned into signals to the process group otherwise.
+///
 /// Try the import-emtran subcommand to get a few hundred tests.
 ///
 /// -> ./maxitest import-emtran && ./maxitext run emtran_mandatory.csv
@@ -92,18 +99,67 @@ struct Run {
     /// Don't show passed tests
     #[arg(short, long)]
     quiet: bool,
+    /// Run both shells in a pseudo-terminal and compare the transcripts, with prompts and the
+    /// echo of typed lines removed. Stdout and stderr can't be told apart in this mode
+    #[arg(long)]
+    pty: bool,
+    /// Regex matching your minishell's prompt, for --pty. Can be repeated, bash's default prompt
+    /// and "> " are always recognized
+    #[arg(long, value_parser = Regex::new)]
+    pty_prompt: Vec<Regex>,
+    /// Keep the echo of typed lines in --pty transcripts
+    #[arg(long)]
+    pty_keep_echo: bool,
+    /// Milliseconds of silence after which the shell is considered ready for the next test
+    /// step. With --pty, lines are typed after this delay if no prompt was recognized
+    /// [default: 300]
+    #[arg(long)]
+    idle: Option<u64>,
     /// Run bash as bash --posix
     #[arg(long)]
     bash_posix: bool,
-    /// Use valgrind to check for memory leaks
+    /// Use valgrind to check for memory leaks. Output is still compared with bash, memory
+    /// errors and behaviour differences are reported separately
     #[arg(short, long)]
     valgrind: bool,
+    /// With --valgrind, also check the processes minishell forks, like builtins in a pipeline or
+    /// children whose exec failed. Programs from /usr and /bin are not checked
+    #[arg(long)]
+    trace_children: bool,
     /// Use valgrind to check for memory leaks
     #[arg(short, long)]
     funcheck: bool,
+    /// Run each test again once for each of the first FAIL_ALLOCS allocations made by minishell,
+    /// with that malloc or calloc failing, and check that it doesn't crash, hang or leak
+    #[arg(long)]
+    fail_allocs: Option<usize>,
+    /// Kill tests that run for longer than this many seconds, 0 to disable. A test can override
+    /// it with its own "timeout" column [default: 10]
+    #[arg(short, long)]
+    timeout: Option<f64>,
+    /// Limit how many processes your user can have while a test runs. This counts all of your
+    /// user's processes, not only the test's
+    #[arg(long)]
+    max_procs: Option<u64>,
+    /// Limit the address space of each process of a test, in MiB. Too low for valgrind and
+    /// sanitizers
+    #[arg(long)]
+    max_memory: Option<u64>,
+    /// Limit the CPU time of each process of a test, in seconds
+    #[arg(long)]
+    max_cpu: Option<u64>,
+    /// Limit the size of the files written by a test, in MiB
+    #[arg(long)]
+    max_file_size: Option<u64>,
+    /// Kill tests once they printed this many KiB, 0 to disable [default: 1024]
+    #[arg(long)]
+    max_output: Option<usize>,
     /// Check for correct error messages
     #[arg(short, long)]
     error_check: bool,
+    /// Don't compare the files left in the test directory by bash and minishell
+    #[arg(long)]
+    no_files_check: bool,
     /// Ignore the ignore list
     #[arg(short = 'i', long)]
     no_ignore: bool,
@@ -169,6 +225,15 @@ fn main() -> Result<(), Error> {
             if cli.valgrind && cli.funcheck {
                 panic!("--valgrind conflicts with --funcheck !");
             }
+            if cli.trace_children && !cli.valgrind {
+                panic!("--trace-children needs --valgrind !");
+            }
+            if cli.fail_allocs.is_some() && (cli.valgrind || cli.funcheck) {
+                panic!("--fail-allocs conflicts with --valgrind and --funcheck !");
+            }
+            if cli.pty && cli.funcheck {
+                panic!("--pty conflicts with --funcheck !");
+            }
             let run_test_files = {
                 let cli = cli.clone();
                 let do_show = cli.tests.len() == 1;
@@ -204,24 +269,80 @@ fn main() -> Result<(), Error> {
 
 fn show(cli: &Run, res: &TestResult, mut f: impl FnMut(&str)) {
     match res {
-        TestResult::Error(out) | TestResult::Failed(out) => f(&format!("{}", out.red())),
+        TestResult::Error(out)
+        | TestResult::Failed(out)
+        | TestResult::MemoryError { output: out, .. } => f(&format!("{}", out.red())),
+        TestResult::Timeout(out) | TestResult::LimitExceeded(out) => {
+            f(&format!("{}", out.magenta()))
+        }
+        TestResult::Crashed { output, .. } => f(&format!("{}", output.red().bold())),
         TestResult::Passed(out) if !cli.quiet => f(&format!("{}", out.green())),
         _ => (),
     }
 }
 
 fn recap(n_tests: usize, ignored: usize, results: &[TestResult]) -> String {
-    let (passed, failed) = results.iter().fold((0, 0), |(p, f), res| match res {
-        TestResult::None => (p, f),
-        TestResult::Error(_) => (p, f),
-        TestResult::Passed(_) => (p + 1, f),
-        TestResult::Failed(_) => (p, f + 1),
-    });
-    format!(
-        "{}{}{}{}",
+    let (passed, failed, memory, timeout, limit) =
+        results
+            .iter()
+            .fold((0, 0, 0, 0, 0), |(p, f, m, t, l), res| match res {
+                TestResult::None => (p, f, m, t, l),
+                TestResult::Error(_) => (p, f, m, t, l),
+                TestResult::Passed(_) => (p + 1, f, m, t, l),
+                TestResult::Failed(_) => (p, f + 1, m, t, l),
+                TestResult::MemoryError { .. } => (p, f, m + 1, t, l),
+                TestResult::Timeout(_) => (p, f, m, t + 1, l),
+                TestResult::LimitExceeded(_) => (p, f, m, t, l + 1),
+                TestResult::Crashed { .. } => (p, f, m, t, l),
+            });
+    let crashed = results
+        .iter()
+        .filter_map(|res| match res {
+            TestResult::Crashed { crash, .. } => Some(crash.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let mut recap = format!(
+        "{}{}{}{}{}{}",
         format!("{passed} passed, ").green(),
         format!("{failed} failed, ").red(),
+        format!("{memory} memory errors, ").bright_red(),
+        format!("{} crashed, ", crashed.len()).red().bold(),
         format!("{ignored} ignored, ").yellow(),
-        format!("{} not run", n_tests - passed - failed).white(),
-    )
+        format!(
+            "{} not run",
+            n_tests - passed - failed - memory - crashed.len() - timeout - limit
+        )
+        .white(),
+    );
+    if timeout > 0 {
+        recap += &format!("\n{}", format!("{timeout} timed out").magenta());
+    }
+    if limit > 0 {
+        recap += &format!("\n{}", format!("{limit} exceeded limits").magenta());
+    }
+    let mut site_counts = HashMap::<&str, usize>::new();
+    for res in results {
+        if let TestResult::MemoryError { sites, .. } = res {
+            for site in sites {
+                *site_counts.entry(site).or_default() += 1;
+            }
+        }
+    }
+    let mut sites = site_counts.into_iter().collect::<Vec<_>>();
+    sites.sort_by(|(a_site, a_count), (b_site, b_count)| {
+        b_count.cmp(a_count).then(a_site.cmp(b_site))
+    });
+    for (site, count) in sites {
+        let tests = match count {
+            1 => "1 test".to_owned(),
+            _ => format!("{count} tests"),
+        };
+        recap += &format!("\n{}", format!("{site}, in {tests}").bright_red());
+    }
+    if !crashed.is_empty() {
+        let crashed = format!("Crashed: {}", crashed.join(", "));
+        recap += &format!("\n{}", crashed.red().bold());
+    }
+    recap
 }
@@ -0,0 +1,104 @@
+use super::steps::signal_name;
+use regex::Regex;
+use std::{
+    fmt::{self, Display},
+    os::unix::process::ExitStatusExt,
+    process::ExitStatus,
+};
+
+/// How many frames of each sanitizer stack trace are shown
+const SANITIZER_FRAMES: usize = 5;
+
+pub struct SanitizerReport {
+    /// "AddressSanitizer: heap-use-after-free on address ..." or a UBSan runtime error
+    pub title: String,
+    pub frames: Vec<String>,
+}
+
+pub struct Crash {
+    pub signal: Option<i32>,
+    pub core_dumped: bool,
+    pub reports: Vec<SanitizerReport>,
+}
+
+impl Crash {
+    /// Finds out if a program crashed, from its status and sanitizer reports in its error output.
+    /// A program killed by the same signal as bash is not considered crashed
+    pub fn detect(status: &ExitStatus, stderr: &[u8], bash_status: &ExitStatus) -> Option<Self> {
+        let signal = status
+            .signal()
+            .filter(|signal| Some(*signal) != bash_status.signal());
+        let reports = sanitizer_reports(&String::from_utf8_lossy(stderr));
+        if signal.is_none() && reports.is_empty() {
+            return None;
+        }
+        Some(Self {
+            signal,
+            core_dumped: signal.is_some() && status.core_dumped(),
+            reports,
+        })
+    }
+
+    /// Short description for the recap
+    pub fn summary(&self) -> String {
+        match (self.signal, self.reports.first()) {
+            (Some(signal), _) => signal_name(signal),
+            (None, Some(report)) => report
+                .title
+                .split(" on ")
+                .next()
+                .unwrap_or_default()
+                .to_owned(),
+            (None, None) => "crash".to_owned(),
+        }
+    }
+}
+
+impl Display for Crash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(signal) = self.signal {
+            write!(f, "Killed by {}", signal_name(signal))?;
+            if self.core_dumped {
+                write!(f, " (core dumped)")?;
+            }
+            writeln!(f)?;
+        }
+        for report in self.reports.iter() {
+            writeln!(f, "{}", report.title)?;
+            for frame in report.frames.iter() {
+                writeln!(f, "    {frame}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn sanitizer_reports(stderr: &str) -> Vec<SanitizerReport> {
+    let error = Regex::new(r"^==\d+==ERROR: (\w+Sanitizer: .*)$").unwrap();
+    let runtime_error = Regex::new(r"^(\S+:\d+:\d+: runtime error: .*)$").unwrap();
+    let frame = Regex::new(r"^\s*(#\d+ .*)$").unwrap();
+    let mut reports = Vec::<SanitizerReport>::new();
+    let mut is_in_stack = false;
+    for line in stderr.lines() {
+        if let Some(title) = error
+            .captures(line)
+            .or_else(|| runtime_error.captures(line))
+        {
+            reports.push(SanitizerReport {
+                title: title[1].to_owned(),
+                frames: vec![],
+            });
+            is_in_stack = true;
+        } else if let (true, Some(found), Some(report)) =
+            (is_in_stack, frame.captures(line), reports.last_mut())
+        {
+            if report.frames.len() < SANITIZER_FRAMES {
+                report.frames.push(found[1].to_owned());
+            }
+        } else if line.trim().is_empty() && reports.last().is_some_and(|r| !r.frames.is_empty()) {
+            // only the first stack trace of a report is the one of the error
+            is_in_stack = false;
+        }
+    }
+    reports
+}
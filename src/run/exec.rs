@@ -1,11 +1,23 @@
+use super::{
+    crash::Crash,
+    inject,
+    limits::Limits,
+    pty,
+    steps::{self, signal_name, Action, Step, StepError, Trigger},
+    tree, valgrind,
+};
 use crate::{test::Test, Run};
 use regex::Regex;
 use std::{
     ffi::OsStr,
     fs,
-    io::{self, Write},
+    io::{self, Read, Write},
+    os::unix::process::{CommandExt, ExitStatusExt},
     path::{Path, PathBuf},
-    process::{Command, Output, Stdio},
+    process::{Child, ChildStdin, Command, ExitStatus, Stdio},
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 use thiserror::Error;
 
@@ -19,6 +31,43 @@ pub enum ExecError {
     Command(io::Error),
     #[error("Error from bwrap, probably missing executable")]
     Bwrap,
+    #[error("Invalid test step: {0}")]
+    Step(#[from] StepError),
+}
+
+/// Valgrind's files are kept next to the test directory, or at the root of the sandbox
+const CHECKER_DIR: &str = ".maxitest";
+/// Valgrind replaces %p with the pid of each process it checks
+const VALGRIND_LOG: &str = "valgrind.%p.log";
+const VALGRIND_XML: &str = "valgrind.%p.xml";
+/// Programs minishell runs that are not worth checking, with --trace-children
+const VALGRIND_SKIPPED_CHILDREN: &str = "/usr/*,/bin/*";
+const VALGRIND_SUPPRESSIONS: &str = "valgrind-suppressions";
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_IDLE_DELAY: Duration = Duration::from_millis(300);
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+pub enum TestStatus {
+    Passed,
+    Failed,
+    Timeout,
+    LimitExceeded,
+    /// With a short description of the crash
+    Crashed(String),
+    /// Valgrind reported errors, whether the behaviour matches bash or not. With the site of each
+    /// error
+    MemoryError(Vec<String>),
+}
+
+struct ExecOutput {
+    status: ExitStatus,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    timed_out: bool,
+    /// Killed after printing more than the output limit
+    truncated: bool,
+    valgrind_processes: Option<Vec<valgrind::Process>>,
+    injection: Option<inject::Report>,
 }
 
 #[derive(Debug, Error)]
@@ -83,15 +132,257 @@ fn ensure_newline(bytes: &mut Vec<u8>) {
     }
 }
 
+fn test_timeout(test: &Test, cli: &Run) -> Option<Duration> {
+    match test.timeout.or(cli.timeout) {
+        Some(secs) if secs > 0. => Some(Duration::from_secs_f64(secs)),
+        Some(_) => None,
+        None => Some(DEFAULT_TIMEOUT),
+    }
+}
+
+/// Kills the whole process group of the child, which also takes down the bwrap sandbox
+fn kill_process_group(child: &Child) {
+    // SAFETY: the child was spawned as a process group leader, so its pid is also the group id
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+}
+
+/// Output captured so far, shared with the thread typing the test
+#[derive(Default)]
+struct Capture {
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    last_activity: Option<Instant>,
+    open_streams: usize,
+    /// How many bytes of output and error are kept
+    cap: Option<usize>,
+    truncated: bool,
+}
+
+fn spawn_reader(
+    mut stream: impl Read + Send + 'static,
+    capture: Arc<Mutex<Capture>>,
+    select: fn(&mut Capture) -> &mut Vec<u8>,
+) -> JoinHandle<io::Result<()>> {
+    capture.lock().unwrap().open_streams += 1;
+    thread::spawn(move || {
+        let mut buf = [0; 4096];
+        let res = loop {
+            match stream.read(&mut buf) {
+                Ok(0) => break Ok(()),
+                Ok(len) => {
+                    let mut capture = capture.lock().unwrap();
+                    let captured = capture.stdout.len() + capture.stderr.len();
+                    let kept = capture
+                        .cap
+                        .map_or(len, |cap| len.min(cap.saturating_sub(captured)));
+                    select(&mut capture).extend_from_slice(&buf[..kept]);
+                    capture.truncated |= kept < len;
+                    capture.last_activity = Some(Instant::now());
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => break Err(err),
+            }
+        };
+        capture.lock().unwrap().open_streams -= 1;
+        res
+    })
+}
+
+/// Writes the lines of the test to the shell, and performs the other steps once their trigger
+/// fires. Keys are turned into the signal a terminal would send to the process group
+fn type_steps(
+    mut stdin: ChildStdin,
+    steps: &[Step],
+    capture: &Mutex<Capture>,
+    process_group: libc::pid_t,
+    idle_delay: Duration,
+) -> io::Result<()> {
+    let mut is_signaled = false;
+    let (mut stdout_at, mut stderr_at) = (0, 0);
+    for step in steps {
+        let (action, trigger) = match step {
+            Step::Line(line) => {
+                match stdin
+                    .write_all(format!("{line}\n").as_bytes())
+                    .and_then(|()| stdin.flush())
+                {
+                    // the shell is allowed to die from a signal before reading everything
+                    Err(err) if is_signaled && err.kind() == io::ErrorKind::BrokenPipe => {
+                        return Ok(())
+                    }
+                    res => res?,
+                }
+                continue;
+            }
+            Step::Action(action, trigger) => (action, trigger),
+        };
+        let started = Instant::now();
+        loop {
+            let capture = capture.lock().unwrap();
+            if capture.open_streams == 0 {
+                return Ok(());
+            }
+            let is_triggered = match trigger {
+                Trigger::Ready => {
+                    let last_activity = capture.last_activity.unwrap_or(started).max(started);
+                    last_activity.elapsed() >= idle_delay
+                }
+                Trigger::After(delay) => started.elapsed() >= *delay,
+                Trigger::Output(text) => {
+                    [&capture.stdout[stdout_at..], &capture.stderr[stderr_at..]]
+                        .iter()
+                        .any(|output| String::from_utf8_lossy(output).contains(text.as_str()))
+                }
+            };
+            if is_triggered {
+                (stdout_at, stderr_at) = (capture.stdout.len(), capture.stderr.len());
+                break;
+            }
+            drop(capture);
+            thread::sleep(WAIT_POLL_INTERVAL);
+        }
+        let signal = match action {
+            Action::Key(key) => key.signal(),
+            Action::Signal(signal) => Some(*signal),
+        };
+        match signal {
+            // SAFETY: the child was spawned as a process group leader
+            Some(signal) => unsafe {
+                libc::kill(-process_group, signal);
+            },
+            // Ctrl-D without a terminal, closes stdin
+            None => return Ok(()),
+        }
+        is_signaled = true;
+    }
+    Ok(())
+}
+
+fn wait_with_timeout(
+    mut child: Child,
+    steps: &[Step],
+    timeout: Option<Duration>,
+    idle_delay: Duration,
+    output_cap: Option<usize>,
+) -> Result<ExecOutput, ExecError> {
+    let stdin = child.stdin.take().unwrap();
+    let capture = Arc::new(Mutex::new(Capture {
+        cap: output_cap,
+        ..Default::default()
+    }));
+    let stdout = spawn_reader(child.stdout.take().unwrap(), capture.clone(), |capture| {
+        &mut capture.stdout
+    });
+    let stderr = spawn_reader(child.stderr.take().unwrap(), capture.clone(), |capture| {
+        &mut capture.stderr
+    });
+    let stdin = {
+        let steps = steps.to_vec();
+        let capture = capture.clone();
+        let process_group = child.id() as libc::pid_t;
+        thread::spawn(move || type_steps(stdin, &steps, &capture, process_group, idle_delay))
+    };
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let (status, timed_out, truncated) = loop {
+        if let Some(status) = child.try_wait()? {
+            break (status, false, false);
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            kill_process_group(&child);
+            break (child.wait()?, true, false);
+        }
+        if capture.lock().unwrap().truncated {
+            kill_process_group(&child);
+            break (child.wait()?, false, true);
+        }
+        thread::sleep(WAIT_POLL_INTERVAL);
+    };
+    let stdin = stdin.join().unwrap();
+    if !timed_out && !truncated {
+        stdin?;
+    }
+    stdout.join().unwrap()?;
+    stderr.join().unwrap()?;
+    let capture = std::mem::take(&mut *capture.lock().unwrap());
+    Ok(ExecOutput {
+        status,
+        stdout: capture.stdout,
+        stderr: capture.stderr,
+        timed_out,
+        truncated,
+        valgrind_processes: None,
+        injection: None,
+    })
+}
+
+/// Memory checker wrapping minishell
+enum Checker {
+    Valgrind {
+        /// Where valgrind reads and writes its files, as seen by minishell
+        dir: PathBuf,
+        trace_children: bool,
+    },
+    Funcheck,
+    /// Makes the allocation with this index fail, with the injector library in this directory
+    FailAlloc {
+        dir: PathBuf,
+        index: usize,
+    },
+}
+
+/// Everything that decides how a shell is spawned, shared by the bash and minishell runs
+struct ExecOptions {
+    bwrap: Option<PathBuf>,
+    timeout: Option<Duration>,
+    idle_delay: Duration,
+    pty: Option<pty::Rules>,
+    limits: Limits,
+}
+
+impl ExecOptions {
+    fn new(test: &Test, cli: &Run, base_path: &Path) -> Self {
+        Self {
+            bwrap: cli
+                .bwrap
+                .then(|| join_path_if_relative(base_path, &cli.exec_paths.bwrap_path)),
+            timeout: test_timeout(test, cli),
+            idle_delay: cli.idle.map_or(DEFAULT_IDLE_DELAY, Duration::from_millis),
+            pty: cli
+                .pty
+                .then(|| pty::Rules::new(&cli.pty_prompt, cli.pty_keep_echo)),
+            limits: Limits::new(cli),
+        }
+    }
+}
+
 fn exec(
     program: impl AsRef<OsStr>,
-    test: &str,
+    steps: &[Step],
     options: &[&str],
-    valgrind: bool,
-    funcheck: bool,
-    bwrap: Option<&Path>,
+    exec_options: &ExecOptions,
     exec_path: &Path,
-) -> Result<Output, ExecError> {
+    checker: Option<&Checker>,
+) -> Result<ExecOutput, ExecError> {
+    let valgrind_checker = match checker {
+        Some(Checker::Valgrind {
+            dir,
+            trace_children,
+        }) => Some((dir, *trace_children)),
+        _ => None,
+    };
+    let (valgrind, funcheck) = (
+        valgrind_checker.is_some(),
+        matches!(checker, Some(Checker::Funcheck)),
+    );
+    let ExecOptions {
+        ref bwrap,
+        timeout,
+        idle_delay,
+        ref pty,
+        limits,
+    } = *exec_options;
     let mut command = if let Some(bwrap) = bwrap {
         let mut command = Command::new(bwrap);
         command
@@ -104,8 +395,11 @@ fn exec(
             .args(["--tmpfs", "/tmp"])
             .args(["--chdir", "/home/maxitester"])
             .arg("--unshare-all")
-            .arg("--die-with-parent")
-            .arg("--new-session");
+            .arg("--die-with-parent");
+        // a new session would detach the shell from its terminal
+        if pty.is_none() {
+            command.arg("--new-session");
+        }
         if valgrind {
             command.args(["--proc", "/proc"]);
         }
@@ -117,23 +411,59 @@ fn exec(
     } else {
         Command::new(&program)
     };
-    if valgrind {
+    if let Some((dir, trace_children)) = valgrind_checker {
         if bwrap.is_some() {
             command.arg("valgrind");
         }
-        command.args([
-            "--leak-check=full",
-            "--show-leak-kinds=all",
-            "--track-origins=yes",
-            "--track-fds=yes",
-            "--errors-for-leak-kinds=all",
-            "--error-exitcode=3", // TODO move 3 to const
-            "--suppressions=../../valgrind-suppressions",
-        ]);
+        // errors are read from the xml report rather than from an exit code, which could be
+        // mistaken for one of minishell's and would hide its real status
+        command
+            .args([
+                "--leak-check=full",
+                "--show-leak-kinds=all",
+                "--track-origins=yes",
+                "--track-fds=yes",
+                "--errors-for-leak-kinds=all",
+            ])
+            .arg("--xml=yes")
+            .arg(format!("--xml-file={}", dir.join(VALGRIND_XML).display()))
+            .arg(format!("--log-file={}", dir.join(VALGRIND_LOG).display()))
+            .arg(format!(
+                "--suppressions={}",
+                dir.join(VALGRIND_SUPPRESSIONS).display()
+            ));
+        // forked children would otherwise write their report in the same files
+        match trace_children {
+            true => command.args([
+                "--trace-children=yes",
+                &format!("--trace-children-skip={VALGRIND_SKIPPED_CHILDREN}"),
+            ]),
+            false => command.arg("--child-silent-after-fork=yes"),
+        };
     }
     if funcheck && bwrap.is_some() {
         command.arg("funcheck");
     }
+    let injector_env = match checker {
+        Some(Checker::FailAlloc { dir, index }) => vec![
+            (
+                "LD_PRELOAD",
+                dir.join(inject::LIBRARY_NAME).into_os_string(),
+            ),
+            ("MAXITEST_FAIL_AT", index.to_string().into()),
+            (
+                "MAXITEST_INJECT_LOG",
+                dir.join(inject::LOG_NAME).into_os_string(),
+            ),
+        ],
+        _ => vec![],
+    };
+    // bwrap itself must not be run with the injector
+    if bwrap.is_some() {
+        for (key, value) in injector_env.iter() {
+            command.arg("--setenv").arg(key).arg(value);
+        }
+    }
     if valgrind || funcheck || bwrap.is_some() {
         command.arg(&program);
     }
@@ -148,19 +478,31 @@ fn exec(
         .env("TERM", "xterm-256color")
         .env("UID", "1000")
         .env("SHLVL", "");
-    command
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped());
-    let mut child = command.spawn().map_err(ExecError::Command)?;
-    let mut stdin = child.stdin.take().unwrap();
-    for line in test.lines() {
-        stdin.write_all(line.as_bytes())?;
-        stdin.write_all(b"\n")?;
-        stdin.flush()?;
-    }
-    drop(stdin);
-    let mut output = child.wait_with_output()?;
+    if bwrap.is_none() {
+        command.envs(injector_env);
+    }
+    limits.apply(&mut command);
+    let mut output = if let Some(rules) = pty {
+        let transcript = pty::exec(command, steps, timeout, idle_delay, limits.output, rules)
+            .map_err(ExecError::Command)?;
+        ExecOutput {
+            status: transcript.status,
+            stdout: rules.apply(&transcript.raw, steps),
+            stderr: vec![],
+            timed_out: transcript.timed_out,
+            truncated: transcript.truncated,
+            valgrind_processes: None,
+            injection: None,
+        }
+    } else {
+        command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        command.process_group(0);
+        let child = command.spawn().map_err(ExecError::Command)?;
+        wait_with_timeout(child, steps, timeout, idle_delay, limits.output)?
+    };
     sort_env(&mut output.stdout);
     sort_env(&mut output.stderr);
     ensure_newline(&mut output.stdout);
@@ -168,34 +510,118 @@ fn exec(
     Ok(output)
 }
 
+/// Reads the report of every process valgrind checked, minishell first
+fn read_valgrind_reports(dir: &Path) -> io::Result<Vec<valgrind::Process>> {
+    let (prefix, suffix) = VALGRIND_XML.split_once("%p").unwrap();
+    let mut processes = vec![];
+    let mut first_err = None;
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        let Some(pid) = name
+            .to_str()
+            .and_then(|name| name.strip_prefix(prefix)?.strip_suffix(suffix))
+        else {
+            continue;
+        };
+        let xml = fs::read_to_string(dir.join(&name))?;
+        let log = fs::read_to_string(dir.join(VALGRIND_LOG.replace("%p", pid))).unwrap_or_default();
+        // a child killed by a signal leaves an unfinished report
+        match valgrind::parse(&xml, &log) {
+            Ok(process) => processes.push(process),
+            Err(err) => drop(first_err.get_or_insert(err)),
+        }
+    }
+    if let (true, Some(err)) = (processes.is_empty(), first_err) {
+        return Err(err);
+    }
+    processes.sort_by_key(|process| process.pid);
+    Ok(processes)
+}
+
 fn exec_minishell(
-    test: &Test,
+    steps: &[Step],
     cli: &Run,
+    exec_options: &ExecOptions,
     base_path: &Path,
     exec_path: &Path,
-) -> Result<Output, ExecError> {
+    fail_alloc: Option<usize>,
+) -> Result<ExecOutput, ExecError> {
     let program_path = join_path_if_relative(base_path, &cli.exec_paths.minishell);
 
     setup_test(exec_path, cli.bwrap)?;
     if cli.bwrap {
         fs::copy(&program_path, exec_path.join(".bin/minishell")).unwrap();
     }
-    let output = exec(
+    let (checker_dir, sandbox_checker_dir) = match cli.bwrap {
+        true => (
+            exec_path.join(CHECKER_DIR),
+            Path::new("/").join(CHECKER_DIR),
+        ),
+        false => {
+            let dir = exec_path.with_extension(&CHECKER_DIR[1..]);
+            (dir.clone(), dir)
+        }
+    };
+    let checker = if let Some(index) = fail_alloc {
+        fs::create_dir(&checker_dir).map_err(SetupError::Io)?;
+        fs::write(checker_dir.join(inject::LIBRARY_NAME), inject::LIBRARY)
+            .map_err(SetupError::Io)?;
+        Some(Checker::FailAlloc {
+            dir: sandbox_checker_dir,
+            index,
+        })
+    } else if cli.valgrind {
+        fs::create_dir(&checker_dir).map_err(SetupError::Io)?;
+        fs::copy(
+            base_path.join(VALGRIND_SUPPRESSIONS),
+            checker_dir.join(VALGRIND_SUPPRESSIONS),
+        )
+        .map_err(SetupError::Io)?;
+        Some(Checker::Valgrind {
+            dir: sandbox_checker_dir,
+            trace_children: cli.trace_children,
+        })
+    } else if cli.funcheck {
+        Some(Checker::Funcheck)
+    } else {
+        None
+    };
+    let mut output = exec(
         if cli.bwrap {
             OsStr::new("/.bin/minishell")
         } else {
             OsStr::new(&program_path)
         },
-        &test.commands,
+        steps,
         &[],
-        cli.valgrind,
-        cli.funcheck,
-        cli.bwrap.then_some(&join_path_if_relative(
-            base_path,
-            &cli.exec_paths.bwrap_path,
-        )),
+        exec_options,
         exec_path,
+        checker.as_ref(),
     );
+    // the report is incomplete if valgrind was killed
+    if let (
+        Ok(
+            output @ ExecOutput {
+                timed_out: false, ..
+            },
+        ),
+        Some(Checker::Valgrind { .. }),
+    ) = (&mut output, &checker)
+    {
+        output.valgrind_processes = Some(read_valgrind_reports(&checker_dir)?);
+    }
+    if let (Ok(output), Some(Checker::FailAlloc { .. })) = (&mut output, &checker) {
+        let log = fs::read_to_string(checker_dir.join(inject::LOG_NAME)).unwrap_or_default();
+        let mut report = inject::Report::parse(&log);
+        report.symbolize(&program_path);
+        output.injection = Some(report);
+    }
+    if matches!(
+        checker,
+        Some(Checker::Valgrind { .. } | Checker::FailAlloc { .. })
+    ) {
+        fs::remove_dir_all(&checker_dir)?;
+    }
     if cli.bwrap {
         fs::remove_file(exec_path.join(".bin/minishell")).unwrap();
     }
@@ -210,11 +636,12 @@ fn adjust_bash_output(bytes: &mut Vec<u8>, bash_path: &Path) {
 }
 
 fn exec_bash(
-    test: &Test,
+    steps: &[Step],
     cli: &Run,
+    exec_options: &ExecOptions,
     base_path: &Path,
     exec_path: &Path,
-) -> Result<Output, ExecError> {
+) -> Result<ExecOutput, ExecError> {
     let bash_path = join_path_if_relative(base_path, &cli.exec_paths.bash);
 
     setup_test(exec_path, cli.bwrap)?;
@@ -222,35 +649,93 @@ fn exec_bash(
     if cli.bash_posix {
         bash_options.push("--posix");
     }
+    if cli.pty {
+        bash_options.push("--norc");
+    }
     let mut output = exec(
         &bash_path,
-        &test.commands,
+        steps,
         &bash_options,
-        cli.valgrind,
-        cli.funcheck,
-        cli.bwrap.then_some(&join_path_if_relative(
-            base_path,
-            &cli.exec_paths.bwrap_path,
-        )),
+        exec_options,
         exec_path,
+        None,
     )?;
     adjust_bash_output(&mut output.stdout, &bash_path);
     adjust_bash_output(&mut output.stderr, &bash_path);
     Ok(output)
 }
 
+/// Runs minishell again for each of its first allocations, with that allocation failing, until
+/// one isn't reached. Reports the paths where it crashed, hung or leaked, returns their sites
+#[allow(clippy::too_many_arguments)]
+fn check_alloc_failures(
+    steps: &[Step],
+    cli: &Run,
+    exec_options: &ExecOptions,
+    base_path: &Path,
+    exec_path: &Path,
+    bash_status: &ExitStatus,
+    max: usize,
+    output: &mut impl io::Write,
+) -> Result<Vec<String>, ExecError> {
+    let mut report = vec![];
+    let mut sites = vec![];
+    for index in 1..=max {
+        let minishell =
+            exec_minishell(steps, cli, exec_options, base_path, exec_path, Some(index))?;
+        let injection = minishell.injection.unwrap_or_default();
+        // minishell made fewer allocations than that
+        let Some(failure) = injection.failures.first() else {
+            break;
+        };
+        let problem = if minishell.timed_out {
+            write!(report, "{failure}Timed out")?;
+            if let Some(timeout) = exec_options.timeout {
+                write!(report, " after {}s", timeout.as_secs_f64())?;
+            }
+            writeln!(report)?;
+            "timeout".to_owned()
+        } else if let Some(crash) = Crash::detect(&minishell.status, &minishell.stderr, bash_status)
+        {
+            write!(report, "{failure}{crash}")?;
+            crash.summary()
+        } else if let Some((blocks, bytes)) = injection.leaks {
+            write!(report, "{failure}")?;
+            writeln!(
+                report,
+                "Exited with {blocks} blocks ({bytes} bytes) still allocated"
+            )?;
+            "leak".to_owned()
+        } else {
+            continue;
+        };
+        sites.push(match failure.caller() {
+            Some(caller) => format!("Allocation failure: {problem} when malloc fails in {caller}"),
+            None => format!("Allocation failure: {problem}"),
+        });
+    }
+    if !report.is_empty() {
+        writeln!(output, "## ALLOCATION FAILURE ##")?;
+        output.write_all(&report)?;
+        writeln!(output, "########################")?;
+    }
+    Ok(sites)
+}
+
 pub fn exec_test(
     test: &Test,
     cli: &Run,
     base_path: &Path,
     exec_path: &Path,
     output: &mut impl io::Write,
-) -> Result<bool, ExecError> {
+) -> Result<TestStatus, ExecError> {
     writeln!(output)?;
     writeln!(output, "##### TEST {:>7} #####", test.id)?;
     writeln!(output, "{}", test.commands)?;
 
-    let bash = exec_bash(test, cli, base_path, exec_path)
+    let steps = steps::parse(&test.commands)?;
+    let exec_options = ExecOptions::new(test, cli, base_path);
+    let bash = exec_bash(&steps, cli, &exec_options, base_path, exec_path)
         .inspect_err(|_| drop(writeln!(output, "# BASH FAILED TO RUN! ##")))?;
 
     if cli.bwrap
@@ -262,7 +747,29 @@ pub fn exec_test(
         return Err(ExecError::Bwrap);
     }
 
-    let minishell = exec_minishell(test, cli, base_path, exec_path)
+    if bash.timed_out {
+        writeln!(output, "#### BASH TIMED OUT! ###")?;
+        return Ok(TestStatus::Timeout);
+    }
+
+    let limits = exec_options.limits;
+    if let Some(exceeded) = limits.exceeded(
+        &bash.status,
+        [&bash.stdout, &bash.stderr],
+        bash.truncated,
+        [&[], &[]],
+    ) {
+        writeln!(output, "### BASH OVER LIMIT! ###")?;
+        writeln!(output, "{exceeded}")?;
+        return Ok(TestStatus::LimitExceeded);
+    }
+
+    let bash_files = match cli.no_files_check {
+        false => Some(tree::snapshot(exec_path)?),
+        true => None,
+    };
+
+    let minishell = exec_minishell(&steps, cli, &exec_options, base_path, exec_path, None)
         .inspect_err(|_| drop(writeln!(output, "#### FAILED TO RUN! ####")))?;
 
     if cli.bwrap
@@ -274,34 +781,119 @@ pub fn exec_test(
         return Err(ExecError::Bwrap);
     }
 
-    if cli.valgrind {
-        match minishell.status.code() {
-            Some(3) => {
-                writeln!(output, "#### VALGRIND ERROR ####")?;
-                if !minishell.stdout.is_empty() {
-                    writeln!(output, "Output:")?;
-                    output.write_all(&minishell.stdout)?;
-                }
-                if !minishell.stderr.is_empty() {
-                    writeln!(output, "Error:")?;
-                    output.write_all(&minishell.stderr)?;
-                }
-                writeln!(output, "########################")?;
-                return Ok(false);
+    let minishell_files = match cli.no_files_check {
+        false => Some(tree::snapshot(exec_path)?),
+        true => None,
+    };
+
+    if minishell.timed_out {
+        writeln!(output, "###### TIMED OUT! ######")?;
+        if let Some(timeout) = exec_options.timeout {
+            writeln!(output, "Killed after {}s", timeout.as_secs_f64())?;
+        }
+        if !minishell.stdout.is_empty() {
+            writeln!(output, "Output:")?;
+            output.write_all(&minishell.stdout)?;
+        }
+        if !minishell.stderr.is_empty() {
+            writeln!(output, "Error:")?;
+            output.write_all(&minishell.stderr)?;
+        }
+        writeln!(output, "########################")?;
+        return Ok(TestStatus::Timeout);
+    }
+
+    if let Some(exceeded) = limits.exceeded(
+        &minishell.status,
+        [&minishell.stdout, &minishell.stderr],
+        minishell.truncated,
+        [&bash.stdout, &bash.stderr],
+    ) {
+        writeln!(output, "### LIMIT EXCEEDED! ####")?;
+        writeln!(output, "{exceeded}")?;
+        if !minishell.stdout.is_empty() {
+            writeln!(output, "Output:")?;
+            output.write_all(&minishell.stdout)?;
+        }
+        if !minishell.stderr.is_empty() {
+            writeln!(output, "Error:")?;
+            output.write_all(&minishell.stderr)?;
+        }
+        writeln!(output, "########################")?;
+        return Ok(TestStatus::LimitExceeded);
+    }
+
+    if let Some(crash) = Crash::detect(&minishell.status, &minishell.stderr, &bash.status) {
+        writeln!(output, "### PROGRAM CRASHED! ###")?;
+        write!(output, "{crash}")?;
+        if !minishell.stdout.is_empty() {
+            writeln!(output, "Output:")?;
+            output.write_all(&minishell.stdout)?;
+        }
+        if !minishell.stderr.is_empty() {
+            writeln!(output, "Error:")?;
+            output.write_all(&minishell.stderr)?;
+        }
+        writeln!(output, "########################")?;
+        return Ok(TestStatus::Crashed(crash.summary()));
+    }
+
+    let valgrind_processes = minishell.valgrind_processes.as_deref().unwrap_or_default();
+    if valgrind_processes
+        .iter()
+        .any(|process| !process.errors.is_empty())
+    {
+        writeln!(output, "#### VALGRIND ERROR ####")?;
+        for process in valgrind_processes {
+            if process.errors.is_empty() {
+                continue;
             }
-            Some(_) => {
-                writeln!(output, "####### SUCCESS! #######")?;
-                return Ok(true); // DESIGN compare with bash instead of success ?
+            if valgrind_processes.len() > 1 {
+                writeln!(output, "In {}:", process.describe(valgrind_processes))?;
+            }
+            for error in process.errors.iter() {
+                write!(output, "{error}")?;
             }
-            _ => (),
         }
+        writeln!(output, "########################")?;
+    }
+    let mut memory_sites = valgrind_processes
+        .iter()
+        .flat_map(|process| {
+            let is_child = valgrind_processes
+                .iter()
+                .any(|parent| parent.pid == process.ppid);
+            process.errors.iter().map(move |error| match is_child {
+                true => format!("{}, in a child process", error.site()),
+                false => error.site(),
+            })
+        })
+        .collect::<Vec<_>>();
+    if let Some(max) = cli.fail_allocs {
+        memory_sites.extend(check_alloc_failures(
+            &steps,
+            cli,
+            &exec_options,
+            base_path,
+            exec_path,
+            &bash.status,
+            max,
+            output,
+        )?);
     }
+    memory_sites.sort();
+    memory_sites.dedup();
+    let memory_errors = !memory_sites.is_empty();
+    let failed = || match memory_errors {
+        true => TestStatus::MemoryError(memory_sites.clone()),
+        false => TestStatus::Failed,
+    };
 
     if cli.funcheck {
         match minishell.status.code() {
             Some(0) => {
                 writeln!(output, "####### SUCCESS! #######")?;
-                return Ok(true); // DESIGN compare with bash instead of success ?
+                return Ok(TestStatus::Passed); // DESIGN compare with bash instead of success ?
             }
             Some(_) => {
                 writeln!(output, "#### FUNCHECK ERROR ####")?;
@@ -314,12 +906,13 @@ pub fn exec_test(
                     output.write_all(&minishell.stderr)?;
                 }
                 writeln!(output, "########################")?;
-                return Ok(false);
+                return Ok(TestStatus::Failed);
             }
             _ => (),
         }
     }
 
+    let has_actions = steps.iter().any(Step::is_action);
     match (bash.status.code(), minishell.status.code()) {
         (Some(bash_code), Some(minishell_code)) => {
             if bash_code != minishell_code {
@@ -334,16 +927,56 @@ pub fn exec_test(
                     output.write_all(&minishell.stderr)?;
                 }
                 writeln!(output, "########################")?;
-                return Ok(false);
+                return Ok(failed());
+            }
+        }
+        (None, None) if has_actions && bash.status.signal() == minishell.status.signal() => {}
+        (None, None) if has_actions => {
+            writeln!(output, "######## FAILED ########")?;
+            writeln!(
+                output,
+                "Expected to be killed by {}, got killed by {}",
+                signal_name(bash.status.signal().unwrap_or_default()),
+                signal_name(minishell.status.signal().unwrap_or_default())
+            )?;
+            writeln!(output, "########################")?;
+            return Ok(failed());
+        }
+        (None, Some(minishell_code)) if has_actions => {
+            writeln!(output, "######## FAILED ########")?;
+            writeln!(
+                output,
+                "Expected to be killed by {}, got status {minishell_code}",
+                signal_name(bash.status.signal().unwrap_or_default())
+            )?;
+            if !minishell.stdout.is_empty() {
+                writeln!(output, "Output:")?;
+                output.write_all(&minishell.stdout)?;
             }
+            if !minishell.stderr.is_empty() {
+                writeln!(output, "Error:")?;
+                output.write_all(&minishell.stderr)?;
+            }
+            writeln!(output, "########################")?;
+            return Ok(failed());
+        }
+        (Some(bash_code), None) if has_actions => {
+            writeln!(output, "######## FAILED ########")?;
+            writeln!(
+                output,
+                "Expected status {bash_code}, got killed by {}",
+                signal_name(minishell.status.signal().unwrap_or_default())
+            )?;
+            writeln!(output, "########################")?;
+            return Ok(failed());
         }
         (None, _) => {
             writeln!(output, "#### BASH CRASHED! #####")?;
-            return Ok(false);
+            return Ok(failed());
         }
         (_, None) => {
             writeln!(output, "### PROGRAM CRASHED! ###")?;
-            return Ok(false);
+            return Ok(failed());
         }
     }
 
@@ -358,7 +991,7 @@ pub fn exec_test(
             output.write_all(&minishell.stderr)?;
         }
         writeln!(output, "########################")?;
-        return Ok(false);
+        return Ok(failed());
     }
 
     if cli.error_check && bash.stderr != minishell.stderr {
@@ -372,12 +1005,39 @@ pub fn exec_test(
         writeln!(output, "Tested error:")?;
         output.write_all(&minishell.stderr)?;
         writeln!(output, "########################")?;
-        return Ok(false);
+        return Ok(failed());
+    }
+
+    if let (Some(bash_files), Some(minishell_files)) = (&bash_files, &minishell_files) {
+        let mut files_diff = vec![];
+        if !tree::compare(bash_files, minishell_files, &mut files_diff)? {
+            writeln!(output, "######## FAILED ########")?;
+            writeln!(output, "Files differ:")?;
+            output.write_all(&files_diff)?;
+            if !minishell.stdout.is_empty() {
+                writeln!(output, "Output:")?;
+                output.write_all(&minishell.stdout)?;
+            }
+            if !minishell.stderr.is_empty() {
+                writeln!(output, "Error:")?;
+                output.write_all(&minishell.stderr)?;
+            }
+            writeln!(output, "########################")?;
+            return Ok(failed());
+        }
+    }
+
+    if memory_errors {
+        writeln!(output, "## BEHAVIOUR MATCHES! ##")?;
+        writeln!(output, "########################")?;
+        return Ok(TestStatus::MemoryError(memory_sites));
     }
 
     writeln!(output, "####### SUCCESS! #######")?;
     if let Some(minishell_code) = minishell.status.code() {
         writeln!(output, "Status: {minishell_code}")?;
+    } else if let Some(signal) = minishell.status.signal() {
+        writeln!(output, "Killed by {}", signal_name(signal))?;
     }
     if !minishell.stdout.is_empty() {
         writeln!(output, "Output:")?;
@@ -388,5 +1048,5 @@ pub fn exec_test(
         output.write_all(&minishell.stderr)?;
     }
     writeln!(output, "########################")?;
-    Ok(true)
+    Ok(TestStatus::Passed)
 }
@@ -0,0 +1,258 @@
+// Preloaded in minishell with --fail-allocs. Makes the Nth malloc or calloc called from the
+// program's own code fail, and reports the blocks it still had allocated when it exited.
+// Everything is written to a log read by maxitest, see inject.rs for its format.
+
+#define _GNU_SOURCE
+#include <dlfcn.h>
+#include <errno.h>
+#include <execinfo.h>
+#include <fcntl.h>
+#include <limits.h>
+#include <link.h>
+#include <stdint.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <unistd.h>
+
+#define MAX_FRAMES 32
+#define LINE_SIZE (PATH_MAX + 64)
+// open addressing table of the blocks allocated by the program, allocated with mmap so it
+// doesn't go through malloc
+#define TABLE_SIZE (1 << 20)
+#define TOMBSTONE ((void *)1)
+
+extern void *__libc_malloc(size_t size);
+extern void *__libc_calloc(size_t count, size_t size);
+extern void *__libc_realloc(void *ptr, size_t size);
+extern void __libc_free(void *ptr);
+
+struct block {
+	void *ptr;
+	size_t size;
+};
+
+static unsigned long fail_at;
+static unsigned long count;
+static char log_path[PATH_MAX];
+static pid_t main_pid;
+static uintptr_t program_start;
+static uintptr_t program_end;
+static uintptr_t program_bias;
+static struct block *blocks;
+static int is_in_hook;
+static int is_overflowed;
+
+static void put_str(char *line, size_t *len, const char *str) {
+	while (*str && *len < LINE_SIZE - 1)
+		line[(*len)++] = *str++;
+}
+
+static void put_num(char *line, size_t *len, uintptr_t num, unsigned base) {
+	char digits[32];
+	size_t n = 0;
+	do {
+		digits[n++] = "0123456789abcdef"[num % base];
+		num /= base;
+	} while (num);
+	while (n && *len < LINE_SIZE - 1)
+		line[(*len)++] = digits[--n];
+}
+
+static void write_log(const char *line, size_t len) {
+	if (!*log_path)
+		return;
+	int fd = open(log_path, O_WRONLY | O_APPEND | O_CREAT | O_CLOEXEC, 0644);
+	if (fd == -1)
+		return;
+	(void)!write(fd, line, len);
+	close(fd);
+}
+
+static int is_from_program(void *address) {
+	return (uintptr_t)address >= program_start && (uintptr_t)address < program_end;
+}
+
+static size_t slot(void *ptr) {
+	return (((uintptr_t)ptr >> 4) * 2654435761u) & (TABLE_SIZE - 1);
+}
+
+static void track(void *ptr, size_t size) {
+	if (!blocks || !ptr)
+		return;
+	for (size_t i = 0, s = slot(ptr); i < TABLE_SIZE; i++, s = (s + 1) & (TABLE_SIZE - 1)) {
+		if (blocks[s].ptr == NULL || blocks[s].ptr == TOMBSTONE) {
+			blocks[s].ptr = ptr;
+			blocks[s].size = size;
+			return;
+		}
+	}
+	is_overflowed = 1;
+}
+
+// Returns 1 and the size of the block if it was tracked
+static int untrack(void *ptr, size_t *size) {
+	if (!blocks || !ptr)
+		return 0;
+	for (size_t i = 0, s = slot(ptr); i < TABLE_SIZE && blocks[s].ptr; i++, s = (s + 1) & (TABLE_SIZE - 1)) {
+		if (blocks[s].ptr == ptr) {
+			blocks[s].ptr = TOMBSTONE;
+			*size = blocks[s].size;
+			return 1;
+		}
+	}
+	return 0;
+}
+
+static void report_failure(void) {
+	void *frames[MAX_FRAMES];
+	int depth = backtrace(frames, MAX_FRAMES);
+	char line[LINE_SIZE];
+	size_t len = 0;
+	Dl_info self;
+	dladdr((void *)report_failure, &self);
+
+	put_str(line, &len, "fail ");
+	put_num(line, &len, count, 10);
+	put_str(line, &len, "\n");
+	write_log(line, len);
+	for (int i = 0; i < depth; i++) {
+		Dl_info info;
+		if (!dladdr(frames[i], &info))
+			continue;
+		if (info.dli_fbase == self.dli_fbase)
+			continue;
+		// return addresses point after the call
+		uintptr_t address = (uintptr_t)frames[i] - 1;
+		len = 0;
+		put_str(line, &len, "frame ");
+		if (is_from_program(frames[i])) {
+			put_num(line, &len, address - program_bias, 16);
+			put_str(line, &len, " -");
+		} else {
+			put_num(line, &len, address - (uintptr_t)info.dli_fbase, 16);
+			put_str(line, &len, " ");
+			put_str(line, &len, info.dli_fname ? info.dli_fname : "?");
+		}
+		put_str(line, &len, "\n");
+		write_log(line, len);
+	}
+}
+
+static int should_fail(void *caller) {
+	if (is_in_hook || !is_from_program(caller))
+		return 0;
+	if (++count != fail_at)
+		return 0;
+	is_in_hook = 1;
+	report_failure();
+	is_in_hook = 0;
+	return 1;
+}
+
+void *malloc(size_t size) {
+	void *caller = __builtin_return_address(0);
+	if (should_fail(caller)) {
+		errno = ENOMEM;
+		return NULL;
+	}
+	void *ptr = __libc_malloc(size);
+	if (is_from_program(caller))
+		track(ptr, size);
+	return ptr;
+}
+
+void *calloc(size_t nmemb, size_t size) {
+	void *caller = __builtin_return_address(0);
+	if (should_fail(caller)) {
+		errno = ENOMEM;
+		return NULL;
+	}
+	void *ptr = __libc_calloc(nmemb, size);
+	if (is_from_program(caller))
+		track(ptr, nmemb * size);
+	return ptr;
+}
+
+void *realloc(void *ptr, size_t size) {
+	size_t old_size;
+	int was_tracked = untrack(ptr, &old_size);
+	void *new_ptr = __libc_realloc(ptr, size);
+	if (new_ptr && (was_tracked || is_from_program(__builtin_return_address(0))))
+		track(new_ptr, size);
+	else if (!new_ptr && size && was_tracked)
+		track(ptr, old_size);
+	return new_ptr;
+}
+
+void free(void *ptr) {
+	size_t size;
+	untrack(ptr, &size);
+	__libc_free(ptr);
+}
+
+static int find_program(struct dl_phdr_info *info, size_t size, void *data) {
+	(void)size;
+	(void)data;
+	program_bias = info->dlpi_addr;
+	program_start = UINTPTR_MAX;
+	program_end = 0;
+	for (int i = 0; i < info->dlpi_phnum; i++) {
+		const ElfW(Phdr) *phdr = &info->dlpi_phdr[i];
+		if (phdr->p_type != PT_LOAD)
+			continue;
+		uintptr_t start = info->dlpi_addr + phdr->p_vaddr;
+		if (start < program_start)
+			program_start = start;
+		if (start + phdr->p_memsz > program_end)
+			program_end = start + phdr->p_memsz;
+	}
+	// the program is always the first object
+	return 1;
+}
+
+__attribute__((constructor)) static void init(void) {
+	const char *fail = getenv("MAXITEST_FAIL_AT");
+	const char *log = getenv("MAXITEST_INJECT_LOG");
+	if (fail)
+		fail_at = strtoul(fail, NULL, 10);
+	if (log && strlen(log) < sizeof(log_path))
+		strcpy(log_path, log);
+	// programs run by minishell are not tested, and its environment must match bash's
+	unsetenv("LD_PRELOAD");
+	unsetenv("MAXITEST_FAIL_AT");
+	unsetenv("MAXITEST_INJECT_LOG");
+	main_pid = getpid();
+	blocks = mmap(NULL, TABLE_SIZE * sizeof(*blocks), PROT_READ | PROT_WRITE,
+		MAP_PRIVATE | MAP_ANONYMOUS | MAP_NORESERVE, -1, 0);
+	if (blocks == MAP_FAILED)
+		blocks = NULL;
+	// loads the unwinder now rather than while an allocation fails
+	void *frames[1];
+	backtrace(frames, 1);
+	dl_iterate_phdr(find_program, NULL);
+}
+
+__attribute__((destructor)) static void report_leaks(void) {
+	// forked children exit with their parent's blocks
+	if (getpid() != main_pid || !blocks || is_overflowed)
+		return;
+	size_t leaked_blocks = 0;
+	size_t leaked_bytes = 0;
+	for (size_t s = 0; s < TABLE_SIZE; s++) {
+		if (blocks[s].ptr && blocks[s].ptr != TOMBSTONE) {
+			leaked_blocks++;
+			leaked_bytes += blocks[s].size;
+		}
+	}
+	char line[LINE_SIZE];
+	size_t len = 0;
+	put_str(line, &len, "exit ");
+	put_num(line, &len, count, 10);
+	put_str(line, &len, " ");
+	put_num(line, &len, leaked_blocks, 10);
+	put_str(line, &len, " ");
+	put_num(line, &len, leaked_bytes, 10);
+	put_str(line, &len, "\n");
+	write_log(line, len);
+}
@@ -0,0 +1,139 @@
+use std::{
+    fmt::{self, Display},
+    path::Path,
+    process::Command,
+};
+
+/// Preloaded in minishell to make one of its allocations fail, built from inject.c
+pub const LIBRARY: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/libmaxitest_inject.so"));
+pub const LIBRARY_NAME: &str = "libmaxitest_inject.so";
+/// Where the library writes what happened, one event per line:
+/// "fail <index>" followed by "frame <offset> <object>" lines, "-" being the program,
+/// and "exit <allocations> <leaked blocks> <leaked bytes>" when minishell exits
+pub const LOG_NAME: &str = "inject.log";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    offset: u64,
+    /// None for minishell itself
+    object: Option<String>,
+    /// Function and location, found with addr2line
+    symbol: Option<String>,
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.symbol, &self.object) {
+            (Some(symbol), _) => write!(f, "{symbol}"),
+            (None, Some(object)) => write!(f, "{object}+{:#x}", self.offset),
+            (None, None) => write!(f, "minishell+{:#x}", self.offset),
+        }
+    }
+}
+
+/// One allocation made to fail
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    pub index: usize,
+    pub frames: Vec<Frame>,
+}
+
+impl Failure {
+    /// The innermost frame of minishell's own code, which called malloc
+    pub fn caller(&self) -> Option<&Frame> {
+        self.frames.iter().find(|frame| frame.object.is_none())
+    }
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Allocation #{} failed", self.index)?;
+        for frame in self.frames.iter() {
+            writeln!(f, "    at {frame}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub failures: Vec<Failure>,
+    /// Blocks and bytes minishell still had allocated when it exited, if it exited normally
+    pub leaks: Option<(usize, usize)>,
+}
+
+impl Report {
+    pub fn parse(log: &str) -> Self {
+        let mut report = Self::default();
+        for line in log.lines() {
+            let mut words = line.split(' ');
+            match (words.next(), words.next(), words.next(), words.next()) {
+                (Some("fail"), Some(index), None, None) => report.failures.push(Failure {
+                    index: index.parse().unwrap_or_default(),
+                    frames: vec![],
+                }),
+                (Some("frame"), Some(offset), Some(object), None) => {
+                    if let Some(failure) = report.failures.last_mut() {
+                        failure.frames.push(Frame {
+                            offset: u64::from_str_radix(offset, 16).unwrap_or_default(),
+                            object: (object != "-").then(|| object.to_owned()),
+                            symbol: None,
+                        });
+                    }
+                }
+                (Some("exit"), Some(_), Some(blocks), Some(bytes)) => {
+                    let blocks = blocks.parse().unwrap_or_default();
+                    if blocks > 0 {
+                        report.leaks = Some((blocks, bytes.parse().unwrap_or_default()));
+                    }
+                }
+                _ => (),
+            }
+        }
+        report
+    }
+
+    /// Names minishell's frames with addr2line, when it is installed and minishell has debug info
+    pub fn symbolize(&mut self, program: &Path) {
+        let frames = self
+            .failures
+            .iter_mut()
+            .flat_map(|failure| failure.frames.iter_mut())
+            .filter(|frame| frame.object.is_none())
+            .collect::<Vec<_>>();
+        if frames.is_empty() {
+            return;
+        }
+        let Ok(output) = Command::new("addr2line")
+            .args(["-f", "-p", "-s", "-e"])
+            .arg(program)
+            .args(frames.iter().map(|frame| format!("{:#x}", frame.offset)))
+            .output()
+        else {
+            return;
+        };
+        let symbols = String::from_utf8_lossy(&output.stdout);
+        for (frame, symbol) in frames.into_iter().zip(symbols.lines()) {
+            // "function at file:line", with ?? for what is unknown
+            frame.symbol = match symbol.split_once(" at ") {
+                _ if symbol.starts_with("??") => None,
+                Some((function, location)) if !location.starts_with("??") => {
+                    Some(format!("{function} ({location})"))
+                }
+                Some((function, _)) => Some(function.to_owned()),
+                None => Some(symbol.to_owned()),
+            };
+        }
+        // what comes before main is not interesting
+        for failure in self.failures.iter_mut() {
+            if let Some(main) = failure.frames.iter().position(|frame| {
+                frame
+                    .symbol
+                    .as_deref()
+                    .is_some_and(|symbol| symbol == "main" || symbol.starts_with("main ("))
+            }) {
+                failure.frames.truncate(main + 1);
+            }
+        }
+    }
+}
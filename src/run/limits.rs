@@ -0,0 +1,155 @@
+use crate::Run;
+use std::{
+    fmt::{self, Display},
+    io,
+    os::unix::process::{CommandExt, ExitStatusExt},
+    process::{Command, ExitStatus},
+};
+
+const KIB: u64 = 1024;
+const MIB: u64 = 1024 * KIB;
+const DEFAULT_OUTPUT_CAP: usize = MIB as usize;
+/// What fork and malloc failures look like in error messages
+const EAGAIN_MESSAGE: &str = "Resource temporarily unavailable";
+const ENOMEM_MESSAGE: &str = "Cannot allocate memory";
+
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        _ if bytes >= MIB && bytes.is_multiple_of(MIB) => format!("{} MiB", bytes / MIB),
+        _ if bytes >= KIB && bytes.is_multiple_of(KIB) => format!("{} KiB", bytes / KIB),
+        _ => format!("{bytes} bytes"),
+    }
+}
+
+/// Resource limits of the shells and everything they run, so that fork bombs and endless output
+/// don't take down the host
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Limits {
+    pub processes: Option<u64>,
+    /// Address space of each process, in bytes
+    pub memory: Option<u64>,
+    /// CPU time of each process, in seconds
+    pub cpu: Option<u64>,
+    /// Size of the files written, in bytes
+    pub file_size: Option<u64>,
+    /// Bytes of output and error kept, the shell is killed past it
+    pub output: Option<usize>,
+}
+
+impl Limits {
+    pub fn new(cli: &Run) -> Self {
+        Self {
+            processes: cli.max_procs,
+            memory: cli.max_memory.map(|mib| mib * MIB),
+            cpu: cli.max_cpu,
+            file_size: cli.max_file_size.map(|mib| mib * MIB),
+            output: match cli.max_output {
+                Some(0) => None,
+                Some(kib) => Some(kib * KIB as usize),
+                None => Some(DEFAULT_OUTPUT_CAP),
+            },
+        }
+    }
+
+    /// Sets the rlimits in the child, before it execs
+    pub fn apply(&self, command: &mut Command) {
+        let limits = [
+            (libc::RLIMIT_NPROC, self.processes),
+            (libc::RLIMIT_AS, self.memory),
+            (libc::RLIMIT_CPU, self.cpu),
+            (libc::RLIMIT_FSIZE, self.file_size),
+        ];
+        if limits.iter().all(|(_, limit)| limit.is_none()) {
+            return;
+        }
+        // SAFETY: only async-signal-safe functions are called between fork and exec
+        unsafe {
+            command.pre_exec(move || {
+                for (resource, limit) in limits {
+                    let Some(limit) = limit else {
+                        continue;
+                    };
+                    let mut current = libc::rlimit {
+                        rlim_cur: 0,
+                        rlim_max: 0,
+                    };
+                    if libc::getrlimit(resource, &mut current) == -1 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    // past the soft limit on CPU time, a process gets SIGXCPU before SIGKILL
+                    let hard = match resource == libc::RLIMIT_CPU {
+                        true => limit + 1,
+                        false => limit,
+                    };
+                    let limit = libc::rlimit {
+                        rlim_cur: limit.min(current.rlim_max),
+                        rlim_max: hard.min(current.rlim_max),
+                    };
+                    if libc::setrlimit(resource, &limit) == -1 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+    }
+
+    /// Finds out if a shell was stopped by one of the limits, from its status and output. Error
+    /// messages caused by a limit only count if bash didn't print them too
+    pub fn exceeded(
+        &self,
+        status: &ExitStatus,
+        outputs: [&[u8]; 2],
+        is_truncated: bool,
+        bash_outputs: [&[u8]; 2],
+    ) -> Option<Exceeded> {
+        let mentions = |outputs: [&[u8]; 2], message: &str| {
+            outputs
+                .iter()
+                .any(|output| String::from_utf8_lossy(output).contains(message))
+        };
+        let is_new_message =
+            |message| mentions(outputs, message) && !mentions(bash_outputs, message);
+        let signal = status.signal();
+        if let (Some(cap), true) = (self.output, is_truncated) {
+            Some(Exceeded::Output(cap))
+        } else if let (Some(cpu), Some(libc::SIGXCPU)) = (self.cpu, signal) {
+            Some(Exceeded::Cpu(cpu))
+        } else if let (Some(size), Some(libc::SIGXFSZ)) = (self.file_size, signal) {
+            Some(Exceeded::FileSize(size))
+        } else if let (Some(processes), true) = (self.processes, is_new_message(EAGAIN_MESSAGE)) {
+            Some(Exceeded::Processes(processes))
+        } else if let (Some(memory), true) = (self.memory, is_new_message(ENOMEM_MESSAGE)) {
+            Some(Exceeded::Memory(memory))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exceeded {
+    Output(usize),
+    Processes(u64),
+    Memory(u64),
+    Cpu(u64),
+    FileSize(u64),
+}
+
+impl Display for Exceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Exceeded::Output(cap) => write!(f, "Output truncated at {}", format_size(cap as u64)),
+            Exceeded::Processes(processes) => {
+                write!(f, "Exceeded process limit ({processes})")
+            }
+            Exceeded::Memory(memory) => {
+                write!(f, "Exceeded address space limit ({})", format_size(memory))
+            }
+            Exceeded::Cpu(cpu) => write!(f, "Exceeded CPU time limit ({cpu}s)"),
+            Exceeded::FileSize(size) => {
+                write!(f, "Exceeded file size limit ({})", format_size(size))
+            }
+        }
+    }
+}
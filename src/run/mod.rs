@@ -1,11 +1,18 @@
+mod crash;
 mod exec;
+mod inject;
+mod limits;
 mod parse;
+mod pty;
+mod steps;
+mod tree;
+mod valgrind;
 
 pub use parse::parse_tests;
 
 use crate::Run;
 use crate::{show, test::Test};
-use exec::{exec_test, ExecError};
+use exec::{exec_test, ExecError, TestStatus};
 use parse::ParseTestError;
 use rayon::prelude::*;
 use std::{env, fs, io};
@@ -32,6 +39,19 @@ pub enum TestResult {
     None,
     Error(String),
     Failed(String),
+    Timeout(String),
+    /// Killed or cut short by one of the resource limits
+    LimitExceeded(String),
+    MemoryError {
+        output: String,
+        /// Where each error or leaked allocation happened
+        sites: Vec<String>,
+    },
+    Crashed {
+        output: String,
+        /// Test id and what happened, like "12 (SIGSEGV)"
+        crash: String,
+    },
     Passed(String),
 }
 
@@ -57,15 +77,27 @@ pub fn run_tests(tests: &[Test], cli: &Run, do_show: bool) -> Result<Vec<TestRes
         let is_success = exec_test(test, cli, &base_path, &exec_path, &mut output);
         let output = String::from_utf8_lossy(&output);
         match is_success {
-            Ok(true) => {
+            Ok(TestStatus::Passed) => {
                 *res = TestResult::Passed(output.to_string());
                 if do_show {
                     show(cli, res, |res| println!("{res}"));
                 }
                 fs::remove_dir_all(&exec_path).map_err(RunError::ClearCurrentDir)?;
             }
-            Ok(false) => {
-                *res = TestResult::Failed(output.to_string());
+            Ok(status) => {
+                *res = match status {
+                    TestStatus::Timeout => TestResult::Timeout(output.to_string()),
+                    TestStatus::LimitExceeded => TestResult::LimitExceeded(output.to_string()),
+                    TestStatus::MemoryError(sites) => TestResult::MemoryError {
+                        output: output.to_string(),
+                        sites,
+                    },
+                    TestStatus::Crashed(crash) => TestResult::Crashed {
+                        output: output.to_string(),
+                        crash: format!("{} ({crash})", test.id),
+                    },
+                    _ => TestResult::Failed(output.to_string()),
+                };
                 if do_show {
                     show(cli, res, |res| println!("{res}"));
                 } else {
@@ -0,0 +1,274 @@
+use super::steps::{Action, Key, Step, Trigger};
+use regex::Regex;
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::process::CommandExt,
+    },
+    process::{Child, Command, ExitStatus, Stdio},
+    ptr,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Wide enough for readline to never wrap a test line
+const PTY_COLUMNS: u16 = 512;
+const PTY_ROWS: u16 = 24;
+const READ_POLL_INTERVAL: Duration = Duration::from_millis(5);
+/// Output must be quiet for this long before the next line is typed after a prompt
+const SETTLE_DELAY: Duration = Duration::from_millis(20);
+/// Prompts of interactive bash run with --norc, and of here-documents
+const DEFAULT_PROMPTS: &[&str] = &[r"bash-\d+\.\d+[$#] ", "> "];
+
+/// How a raw terminal transcript is cleaned before comparison
+pub struct Rules {
+    prompts: Vec<Regex>,
+    keep_echo: bool,
+    ansi: Regex,
+}
+
+impl Rules {
+    pub fn new(prompts: &[Regex], keep_echo: bool) -> Self {
+        let prompts = DEFAULT_PROMPTS
+            .iter()
+            .copied()
+            .chain(prompts.iter().map(Regex::as_str))
+            .map(|prompt| Regex::new(&format!("^(?:{prompt})")).unwrap())
+            .collect();
+        Self {
+            prompts,
+            keep_echo,
+            ansi: Regex::new(
+                r"\x1b(?:\[[0-?]*[ -/]*[@-~]|\][^\x07\x1b]*(?:\x07|\x1b\\)|[@-Z\\-_])|[\r\x07]",
+            )
+            .unwrap(),
+        }
+    }
+
+    fn strip_ansi(&self, str: &str) -> String {
+        self.ansi.replace_all(str, "").into_owned()
+    }
+
+    fn strip_prompts<'a>(&self, mut line: &'a str) -> &'a str {
+        while let Some(len) = self
+            .prompts
+            .iter()
+            .filter_map(|prompt| prompt.find(line))
+            .map(|found| found.end())
+            .find(|len| *len > 0)
+        {
+            line = &line[len..];
+        }
+        line
+    }
+
+    /// True if the shell is waiting on a prompt at the end of this output
+    fn ends_with_prompt(&self, raw: &[u8]) -> bool {
+        let str = self.strip_ansi(&String::from_utf8_lossy(raw));
+        let last_line = str.rsplit('\n').next().unwrap_or_default();
+        !last_line.is_empty() && self.strip_prompts(last_line).is_empty()
+    }
+
+    /// Removes escape sequences, prompts and the echo of typed lines from a transcript
+    pub fn apply(&self, raw: &[u8], steps: &[Step]) -> Vec<u8> {
+        let str = self.strip_ansi(&String::from_utf8_lossy(raw));
+        let mut input = steps
+            .iter()
+            .filter_map(|step| match step {
+                Step::Line(line) => Some(line.as_str()),
+                Step::Action(..) => None,
+            })
+            .peekable();
+        let mut transcript = String::new();
+        for line in str.split_inclusive('\n') {
+            let (content, newline) = match line.strip_suffix('\n') {
+                Some(content) => (content, "\n"),
+                None => (line, ""),
+            };
+            let stripped = self.strip_prompts(content);
+            if stripped.is_empty() && !content.is_empty() {
+                continue;
+            }
+            if !self.keep_echo && input.peek() == Some(&stripped) {
+                input.next();
+                continue;
+            }
+            transcript += stripped;
+            transcript += newline;
+        }
+        transcript.into_bytes()
+    }
+}
+
+pub struct Transcript {
+    pub status: ExitStatus,
+    pub raw: Vec<u8>,
+    pub timed_out: bool,
+    /// Killed after printing more than the output limit
+    pub truncated: bool,
+}
+
+fn open_pty() -> io::Result<(OwnedFd, OwnedFd)> {
+    let (mut master, mut slave) = (-1, -1);
+    let winsize = libc::winsize {
+        ws_row: PTY_ROWS,
+        ws_col: PTY_COLUMNS,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    // SAFETY: both pointers are valid, and the fds are owned right after a successful call
+    let (master, slave) = unsafe {
+        if libc::openpty(
+            &mut master,
+            &mut slave,
+            ptr::null_mut(),
+            ptr::null(),
+            &winsize,
+        ) != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave))
+    };
+    for fd in [&master, &slave] {
+        // SAFETY: fd is valid
+        if unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok((master, slave))
+}
+
+/// Sends a signal to the foreground process group of the terminal, or to the session leader's
+fn signal_foreground(master: &File, child: &Child, signal: i32) {
+    // SAFETY: the fd is valid, and the child is a session leader so its pid is a process group id
+    unsafe {
+        let foreground = match libc::tcgetpgrp(master.as_raw_fd()) {
+            -1 => child.id() as libc::pid_t,
+            foreground => foreground,
+        };
+        libc::kill(-foreground, signal);
+    }
+}
+
+/// Runs the command as a session leader on a new terminal, performing each step of the test once
+/// its trigger fires, then types Ctrl-D. Lines are typed after a prompt shows up, or after the
+/// output stayed quiet for a while
+pub fn exec(
+    mut command: Command,
+    steps: &[Step],
+    timeout: Option<Duration>,
+    idle_delay: Duration,
+    output_cap: Option<usize>,
+    rules: &Rules,
+) -> io::Result<Transcript> {
+    let (master, slave) = open_pty()?;
+    command
+        .stdin(Stdio::from(slave.try_clone()?))
+        .stdout(Stdio::from(slave.try_clone()?))
+        .stderr(Stdio::from(slave));
+    // SAFETY: only async-signal-safe functions are called between fork and exec
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() == -1 || libc::ioctl(0, libc::TIOCSCTTY, 0) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut child = command.spawn()?;
+    // closes our copies of the slave side, so that reads fail once the session is gone
+    drop(command);
+
+    let mut reader = File::from(master.try_clone()?);
+    let mut writer = File::from(master);
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buf = [0; 4096];
+        while let Ok(len @ 1..) = reader.read(&mut buf) {
+            if tx.send(buf[..len].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let mut steps = steps.iter().peekable();
+    let mut raw = vec![];
+    let mut is_input_done = false;
+    let mut last_activity = Instant::now();
+    let mut last_input = Instant::now();
+    let mut last_input_at = 0;
+    let mut timed_out = false;
+    let mut truncated = false;
+    loop {
+        match rx.recv_timeout(READ_POLL_INTERVAL) {
+            Ok(chunk) => {
+                raw.extend(chunk);
+                last_activity = Instant::now();
+            }
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        if let Some(cap) = output_cap.filter(|cap| raw.len() > *cap) {
+            raw.truncate(cap);
+            truncated = true;
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            timed_out = true;
+        }
+        if timed_out || truncated {
+            // SAFETY: the child is a session leader, so its pid is also its process group id
+            unsafe {
+                libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+            }
+            break;
+        }
+        if is_input_done || child.try_wait()?.is_some() {
+            continue;
+        }
+        let idle = last_activity.elapsed();
+        let is_ready = idle >= SETTLE_DELAY
+            && (idle >= idle_delay || rules.ends_with_prompt(&raw[last_input_at..]));
+        let is_triggered = match steps.peek() {
+            Some(Step::Line(_) | Step::Action(_, Trigger::Ready)) | None => is_ready,
+            Some(Step::Action(_, Trigger::After(delay))) => last_input.elapsed() >= *delay,
+            Some(Step::Action(_, Trigger::Output(text))) => {
+                String::from_utf8_lossy(&raw[last_input_at..]).contains(text.as_str())
+            }
+        };
+        if !is_triggered {
+            continue;
+        }
+        // the shell may exit before reading everything, in which case writing fails
+        let written = match steps.next() {
+            Some(Step::Line(line)) => writer
+                .write_all(line.as_bytes())
+                .and_then(|()| writer.write_all(b"\n")),
+            Some(Step::Action(Action::Key(key), _)) => writer.write_all(&[key.byte()]),
+            Some(Step::Action(Action::Signal(signal), _)) => {
+                signal_foreground(&writer, &child, *signal);
+                Ok(())
+            }
+            None => {
+                is_input_done = true;
+                writer.write_all(&[Key::CtrlD.byte()])
+            }
+        };
+        if written.is_err() {
+            is_input_done = true;
+        }
+        last_input_at = raw.len();
+        last_activity = Instant::now();
+        last_input = Instant::now();
+    }
+    Ok(Transcript {
+        status: child.wait()?,
+        raw,
+        timed_out,
+        truncated,
+    })
+}
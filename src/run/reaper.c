@@ -0,0 +1,129 @@
+// Runs a shell in the bwrap sandbox, where everything dies along with the pid namespace once the
+// shell exits. Before that, lists the processes the shell left behind and kills them, then exits
+// like the shell did.
+// Usage: maxitest-reaper <log> <program> [args...]
+
+#define _GNU_SOURCE
+#include <errno.h>
+#include <dirent.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+// leftover processes get this long to exit on their own
+#define GRACE_MS 100
+#define POLL_MS 10
+
+// Writes "<state> <command line>" of a process, like "S sleep 10"
+static void write_process(FILE *log, long pid) {
+	char path[64];
+	char stat[4096];
+	char cmdline[4096];
+	size_t len = 0;
+	char state = '?';
+	const char *comm = "?";
+
+	snprintf(path, sizeof(path), "/proc/%ld/stat", pid);
+	FILE *file = fopen(path, "r");
+	if (file) {
+		len = fread(stat, 1, sizeof(stat) - 1, file);
+		fclose(file);
+	}
+	stat[len] = '\0';
+	// the command name is in parentheses and can contain anything, the state comes after it
+	char *open = strchr(stat, '(');
+	char *close = strrchr(stat, ')');
+	if (open && close && close > open && close[1] == ' ') {
+		*close = '\0';
+		comm = open + 1;
+		state = close[2];
+	}
+
+	len = 0;
+	snprintf(path, sizeof(path), "/proc/%ld/cmdline", pid);
+	file = fopen(path, "r");
+	if (file) {
+		len = fread(cmdline, 1, sizeof(cmdline) - 1, file);
+		fclose(file);
+	}
+	// arguments are separated by null bytes
+	while (len && cmdline[len - 1] == '\0')
+		len--;
+	for (size_t i = 0; i < len; i++)
+		if (cmdline[i] == '\0')
+			cmdline[i] = ' ';
+	cmdline[len] = '\0';
+	fprintf(log, "%c %s\n", state, len ? cmdline : comm);
+}
+
+// Returns how many processes are left besides the sandbox's init and this one, listing them in
+// the log if there is one
+static int list_processes(FILE *log) {
+	DIR *dir = opendir("/proc");
+	if (!dir)
+		return 0;
+	int count = 0;
+	struct dirent *entry;
+	while ((entry = readdir(dir))) {
+		char *end;
+		long pid = strtol(entry->d_name, &end, 10);
+		if (*end || pid <= 1 || pid == getpid())
+			continue;
+		count++;
+		if (log)
+			write_process(log, pid);
+	}
+	closedir(dir);
+	return count;
+}
+
+int main(int argc, char **argv) {
+	if (argc < 3) {
+		fprintf(stderr, "usage: %s <log> <program> [args...]\n", argv[0]);
+		return 2;
+	}
+	pid_t pid = fork();
+	if (pid == -1) {
+		perror("fork");
+		return 2;
+	}
+	if (pid == 0) {
+		execvp(argv[2], argv + 2);
+		perror(argv[2]);
+		_exit(127);
+	}
+	// signals meant for the shell, like Ctrl-C in a terminal, must not kill the reaper
+	for (int sig = 1; sig < NSIG; sig++)
+		if (sig != SIGCHLD && sig != SIGKILL && sig != SIGSTOP)
+			signal(sig, SIG_IGN);
+	// so that the shell's output ends with the shell
+	close(STDIN_FILENO);
+	close(STDOUT_FILENO);
+	close(STDERR_FILENO);
+
+	int status;
+	while (waitpid(pid, &status, 0) == -1)
+		if (errno != EINTR)
+			return 2;
+	struct timespec poll = {0, POLL_MS * 1000000L};
+	for (int waited = 0; waited < GRACE_MS && list_processes(NULL); waited += POLL_MS)
+		nanosleep(&poll, NULL);
+	if (list_processes(NULL)) {
+		FILE *log = fopen(argv[1], "w");
+		if (log) {
+			list_processes(log);
+			fclose(log);
+		}
+		kill(-1, SIGKILL);
+	}
+
+	if (WIFSIGNALED(status)) {
+		signal(WTERMSIG(status), SIG_DFL);
+		raise(WTERMSIG(status));
+	}
+	return WIFEXITED(status) ? WEXITSTATUS(status) : 128 + WTERMSIG(status);
+}
@@ -0,0 +1,166 @@
+use std::time::Duration;
+use thiserror::Error;
+
+/// Lines starting with this are test steps, every other line is typed as is
+pub const STEP_PREFIX: &str = "@@ ";
+
+const SIGNALS: &[(&str, i32)] = &[
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("ILL", libc::SIGILL),
+    ("TRAP", libc::SIGTRAP),
+    ("ABRT", libc::SIGABRT),
+    ("BUS", libc::SIGBUS),
+    ("FPE", libc::SIGFPE),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("SEGV", libc::SIGSEGV),
+    ("USR2", libc::SIGUSR2),
+    ("PIPE", libc::SIGPIPE),
+    ("ALRM", libc::SIGALRM),
+    ("TERM", libc::SIGTERM),
+    ("CHLD", libc::SIGCHLD),
+    ("CONT", libc::SIGCONT),
+    ("STOP", libc::SIGSTOP),
+    ("TSTP", libc::SIGTSTP),
+    ("TTIN", libc::SIGTTIN),
+    ("TTOU", libc::SIGTTOU),
+    ("XCPU", libc::SIGXCPU),
+    ("XFSZ", libc::SIGXFSZ),
+    ("SYS", libc::SIGSYS),
+];
+
+pub fn signal_name(signal: i32) -> String {
+    match SIGNALS.iter().find(|(_, number)| *number == signal) {
+        Some((name, _)) => format!("SIG{name}"),
+        None => format!("signal {signal}"),
+    }
+}
+
+fn parse_signal(str: &str) -> Option<i32> {
+    let name = str.strip_prefix("SIG").unwrap_or(str);
+    SIGNALS
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(name))
+        .map(|(_, number)| *number)
+        .or_else(|| str.parse().ok())
+}
+
+#[derive(Debug, Error)]
+pub enum StepError {
+    #[error("Unknown test step action {0:?}, expected ctrl-c, ctrl-\\, ctrl-d or signal <NAME>")]
+    Action(String),
+    #[error("Unknown signal {0:?}")]
+    Signal(String),
+    #[error("Unknown test step trigger {0:?}, expected after <N>ms or when <TEXT>")]
+    Trigger(String),
+    #[error("Invalid delay {0:?}, expected a number of ms or s")]
+    Delay(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    CtrlC,
+    CtrlBackslash,
+    CtrlD,
+}
+
+impl Key {
+    /// What the terminal receives when the key is typed
+    pub fn byte(self) -> u8 {
+        match self {
+            Key::CtrlC => 0x03,
+            Key::CtrlBackslash => 0x1c,
+            Key::CtrlD => 0x04,
+        }
+    }
+
+    /// What the terminal would send to the foreground process group, without a terminal
+    pub fn signal(self) -> Option<i32> {
+        match self {
+            Key::CtrlC => Some(libc::SIGINT),
+            Key::CtrlBackslash => Some(libc::SIGQUIT),
+            Key::CtrlD => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Key(Key),
+    Signal(i32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trigger {
+    /// Once the shell is back to a prompt, or its output stayed quiet for a while
+    Ready,
+    After(Duration),
+    /// Once this text is printed after the previous step
+    Output(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    Line(String),
+    Action(Action, Trigger),
+}
+
+impl Step {
+    pub fn is_action(&self) -> bool {
+        matches!(self, Step::Action(..))
+    }
+}
+
+fn parse_delay(str: &str) -> Result<Duration, StepError> {
+    let err = || StepError::Delay(str.to_owned());
+    if let Some(ms) = str.strip_suffix("ms") {
+        ms.parse().map(Duration::from_millis).map_err(|_| err())
+    } else if let Some(secs) = str.strip_suffix('s') {
+        secs.parse()
+            .map_err(|_| err())
+            .and_then(|secs| Duration::try_from_secs_f64(secs).map_err(|_| err()))
+    } else {
+        Err(err())
+    }
+}
+
+/// Parses a step line without its prefix, like "ctrl-c after 200ms" or "signal TERM when $ "
+fn parse_step(str: &str) -> Result<Step, StepError> {
+    let (action, rest) = match str.trim_start().split_once(' ') {
+        Some((action, rest)) => (action, rest.trim_start()),
+        None => (str.trim(), ""),
+    };
+    let (action, rest) = match action.to_ascii_lowercase().as_str() {
+        "ctrl-c" => (Action::Key(Key::CtrlC), rest),
+        "ctrl-\\" => (Action::Key(Key::CtrlBackslash), rest),
+        "ctrl-d" => (Action::Key(Key::CtrlD), rest),
+        "signal" => {
+            let (signal, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+            let signal = parse_signal(signal).ok_or(StepError::Signal(signal.to_owned()))?;
+            (Action::Signal(signal), rest.trim_start())
+        }
+        _ => return Err(StepError::Action(action.to_owned())),
+    };
+    let trigger = if rest.is_empty() {
+        Trigger::Ready
+    } else if let Some(delay) = rest.strip_prefix("after ") {
+        Trigger::After(parse_delay(delay.trim())?)
+    } else if let Some(text) = rest.strip_prefix("when ") {
+        Trigger::Output(text.to_owned())
+    } else {
+        return Err(StepError::Trigger(rest.to_owned()));
+    };
+    Ok(Step::Action(action, trigger))
+}
+
+pub fn parse(commands: &str) -> Result<Vec<Step>, StepError> {
+    commands
+        .lines()
+        .map(|line| match line.strip_prefix(STEP_PREFIX) {
+            Some(step) => parse_step(step),
+            None => Ok(Step::Line(line.to_owned())),
+        })
+        .collect()
+}
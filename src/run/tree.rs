@@ -0,0 +1,162 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Write},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Entry {
+    File { mode: u32, content: Vec<u8> },
+    Dir { mode: u32 },
+    Symlink { target: PathBuf },
+    Other { mode: u32 },
+}
+
+impl Entry {
+    fn kind(&self) -> &'static str {
+        match self {
+            Entry::File { .. } => "file",
+            Entry::Dir { .. } => "directory",
+            Entry::Symlink { .. } => "symlink",
+            Entry::Other { .. } => "special file",
+        }
+    }
+}
+
+/// Every entry found under a test directory, indexed by path relative to that directory
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tree(BTreeMap<PathBuf, Entry>);
+
+pub fn snapshot(root: &Path) -> io::Result<Tree> {
+    fn walk(root: &Path, dir: &Path, tree: &mut Tree) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let metadata = fs::symlink_metadata(&path)?;
+            let mode = metadata.permissions().mode() & 0o7777;
+            let entry = if metadata.is_symlink() {
+                Entry::Symlink {
+                    target: fs::read_link(&path)?,
+                }
+            } else if metadata.is_dir() {
+                walk(root, &path, tree)?;
+                Entry::Dir { mode }
+            } else if metadata.is_file() {
+                match fs::read(&path) {
+                    Ok(content) => Entry::File { mode, content },
+                    // unreadable files are compared on their mode only
+                    Err(err) if err.kind() == io::ErrorKind::PermissionDenied => Entry::File {
+                        mode,
+                        content: vec![],
+                    },
+                    Err(err) => Err(err)?,
+                }
+            } else {
+                Entry::Other { mode }
+            };
+            let relative = path.strip_prefix(root).unwrap_or(&path).to_owned();
+            tree.0.insert(relative, entry);
+        }
+        Ok(())
+    }
+    let mut tree = Tree::default();
+    walk(root, root, &mut tree)?;
+    Ok(tree)
+}
+
+/// Writes a report of every difference between both trees, returns false if there was any
+pub fn compare(expected: &Tree, tested: &Tree, output: &mut impl Write) -> io::Result<bool> {
+    let mut is_same = true;
+    for (path, expected_entry) in expected.0.iter() {
+        let Some(tested_entry) = tested.0.get(path) else {
+            writeln!(output, "Missing {} {path:?}", expected_entry.kind())?;
+            is_same = false;
+            continue;
+        };
+        if expected_entry == tested_entry {
+            continue;
+        }
+        is_same = false;
+        match (expected_entry, tested_entry) {
+            (
+                Entry::File {
+                    mode: expected_mode,
+                    content: expected_content,
+                },
+                Entry::File {
+                    mode: tested_mode,
+                    content: tested_content,
+                },
+            ) => {
+                if expected_mode != tested_mode {
+                    writeln!(
+                        output,
+                        "Mode of file {path:?}: expected {expected_mode:o}, got {tested_mode:o}"
+                    )?;
+                }
+                if expected_content != tested_content {
+                    writeln!(output, "Content of file {path:?} differs")?;
+                    writeln!(output, "Expected content:")?;
+                    write_content(expected_content, output)?;
+                    writeln!(output, "Tested content:")?;
+                    write_content(tested_content, output)?;
+                }
+            }
+            (
+                Entry::Dir {
+                    mode: expected_mode,
+                },
+                Entry::Dir { mode: tested_mode },
+            )
+            | (
+                Entry::Other {
+                    mode: expected_mode,
+                },
+                Entry::Other { mode: tested_mode },
+            ) => {
+                writeln!(
+                    output,
+                    "Mode of {} {path:?}: expected {expected_mode:o}, got {tested_mode:o}",
+                    expected_entry.kind()
+                )?;
+            }
+            (
+                Entry::Symlink {
+                    target: expected_target,
+                },
+                Entry::Symlink {
+                    target: tested_target,
+                },
+            ) => {
+                writeln!(
+                    output,
+                    "Symlink {path:?}: expected target {expected_target:?}, got {tested_target:?}"
+                )?;
+            }
+            _ => {
+                writeln!(
+                    output,
+                    "Expected {} {path:?}, got {}",
+                    expected_entry.kind(),
+                    tested_entry.kind()
+                )?;
+            }
+        }
+    }
+    for (path, tested_entry) in tested.0.iter() {
+        if !expected.0.contains_key(path) {
+            writeln!(output, "Unexpected {} {path:?}", tested_entry.kind())?;
+            is_same = false;
+        }
+    }
+    Ok(is_same)
+}
+
+fn write_content(content: &[u8], output: &mut impl Write) -> io::Result<()> {
+    output.write_all(content)?;
+    if !content.is_empty() && !content.ends_with(b"\n") {
+        writeln!(output)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,283 @@
+use regex::Regex;
+use roxmltree::{Document, Node};
+use std::{
+    fmt::{self, Display},
+    io,
+};
+
+/// How many frames of each stack trace are shown
+const SHOWN_FRAMES: usize = 6;
+/// How many frames of the allocation or error stack identify its site
+const SITE_FRAMES: usize = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    function: Option<String>,
+    file: Option<String>,
+    line: Option<u32>,
+    object: Option<String>,
+}
+
+impl Frame {
+    fn parse(node: Node) -> Self {
+        let child = |name| {
+            node.children()
+                .find(|child| child.has_tag_name(name))
+                .and_then(|child| child.text())
+                .map(str::to_owned)
+        };
+        Self {
+            function: child("fn"),
+            file: child("file"),
+            line: child("line").and_then(|line| line.parse().ok()),
+            object: child("obj"),
+        }
+    }
+
+    /// Frames from valgrind's own replacements of malloc, free and so on
+    fn is_valgrind(&self) -> bool {
+        self.file
+            .as_deref()
+            .is_some_and(|file| file.starts_with("vg_replace_"))
+            || self
+                .object
+                .as_deref()
+                .is_some_and(|object| object.contains("/valgrind/"))
+    }
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.function.as_deref().unwrap_or("???"))?;
+        match (&self.file, self.line, &self.object) {
+            (Some(file), Some(line), _) => write!(f, " ({file}:{line})"),
+            (Some(file), None, _) => write!(f, " ({file})"),
+            (None, _, Some(object)) => write!(f, " (in {object})"),
+            (None, _, None) => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Category {
+    Leak,
+    InvalidAccess,
+    Uninitialised,
+    FileDescriptor,
+    Other,
+}
+
+impl Category {
+    fn from_kind(kind: &str) -> Self {
+        match kind {
+            _ if kind.starts_with("Leak_") => Self::Leak,
+            "InvalidRead" | "InvalidWrite" | "InvalidFree" | "MismatchedFree" | "InvalidJump"
+            | "Overlap" => Self::InvalidAccess,
+            "UninitCondition" | "UninitValue" | "SyscallParam" => Self::Uninitialised,
+            _ if kind.starts_with("Fd") => Self::FileDescriptor,
+            _ => Self::Other,
+        }
+    }
+}
+
+impl Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Category::Leak => "Leak",
+            Category::InvalidAccess => "Invalid access",
+            Category::Uninitialised => "Uninitialised value",
+            Category::FileDescriptor => "File descriptor",
+            Category::Other => "Error",
+        })
+    }
+}
+
+/// One error reported by valgrind, a leak record or an invalid memory use
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    pub category: Category,
+    pub kind: String,
+    pub what: String,
+    pub stack: Vec<Frame>,
+    /// Details about the address involved, like where it was allocated or freed
+    pub aux: Vec<(String, Vec<Frame>)>,
+}
+
+impl Error {
+    fn parse(node: Node) -> Option<Self> {
+        let kind = node
+            .children()
+            .find(|child| child.has_tag_name("kind"))?
+            .text()?
+            .to_owned();
+        let mut what = None;
+        let mut stacks = vec![];
+        let mut aux = vec![];
+        for child in node.children() {
+            match child.tag_name().name() {
+                "what" => what = child.text().map(str::to_owned),
+                "xwhat" => {
+                    what = child
+                        .children()
+                        .find(|child| child.has_tag_name("text"))
+                        .and_then(|text| text.text())
+                        .map(str::to_owned)
+                }
+                "auxwhat" => aux.push((child.text().unwrap_or_default().to_owned(), vec![])),
+                "stack" => {
+                    let frames = child
+                        .children()
+                        .filter(|frame| frame.has_tag_name("frame"))
+                        .map(Frame::parse)
+                        .collect();
+                    // the first stack is the error's, the next ones follow an auxwhat
+                    match aux.last_mut() {
+                        Some((_, aux_stack)) if !stacks.is_empty() => *aux_stack = frames,
+                        _ => stacks.push(frames),
+                    }
+                }
+                _ => (),
+            }
+        }
+        Some(Self {
+            category: Category::from_kind(&kind),
+            what: what.unwrap_or_else(|| kind.clone()),
+            kind,
+            stack: stacks.into_iter().next().unwrap_or_default(),
+            aux,
+        })
+    }
+
+    fn user_frames(&self) -> impl Iterator<Item = &Frame> {
+        self.stack.iter().filter(|frame| !frame.is_valgrind())
+    }
+
+    /// Where the leaked memory was allocated or where the error happened, which is the same
+    /// across tests, unlike the error description
+    pub fn site(&self) -> String {
+        let frames = self
+            .user_frames()
+            .take(SITE_FRAMES)
+            .map(|frame| frame.to_string())
+            .collect::<Vec<_>>();
+        let kind = self.kind.strip_prefix("Leak_").unwrap_or(&self.kind);
+        match frames.is_empty() {
+            true => format!("{}: {kind}", self.category),
+            false => format!("{}: {kind} at {}", self.category, frames.join(" <- ")),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}: {}", self.category, self.what)?;
+        for frame in self.user_frames().take(SHOWN_FRAMES) {
+            writeln!(f, "    at {frame}")?;
+        }
+        for (what, stack) in self.aux.iter() {
+            writeln!(f, "  {what}")?;
+            for frame in stack
+                .iter()
+                .filter(|frame| !frame.is_valgrind())
+                .take(SHOWN_FRAMES)
+            {
+                writeln!(f, "    at {frame}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Errors of one process, minishell itself or one of its forks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Process {
+    pub pid: u32,
+    pub ppid: u32,
+    pub errors: Vec<Error>,
+}
+
+impl Process {
+    /// Names the process relative to the others of the same run
+    pub fn describe(&self, processes: &[Process]) -> String {
+        let parent = processes.iter().find(|process| process.pid == self.ppid);
+        match parent {
+            None => format!("minishell (pid {})", self.pid),
+            Some(parent) if processes.iter().all(|process| process.pid != parent.ppid) => {
+                format!("child process {} of minishell", self.pid)
+            }
+            Some(parent) => format!("child process {} of {}", self.pid, parent.pid),
+        }
+    }
+}
+
+/// Reads the open file descriptors listed in a text log, for valgrind versions that don't report
+/// them as errors
+fn fd_errors(log: &str) -> Vec<Error> {
+    let prefix = Regex::new(r"^==\d+== ?").unwrap();
+    let open = Regex::new(r"^Open (?:file descriptor|\S+ socket|pipe) ?\d*.*$").unwrap();
+    let frame =
+        Regex::new(r"^\s+(?:at|by) 0x[0-9A-F]+: (\S+) \((?:in )?([^:)]+)(?::(\d+))?\)$").unwrap();
+    let mut errors = Vec::<Error>::new();
+    let mut is_in_stack = false;
+    for line in log.lines() {
+        let line = prefix.replace(line, "");
+        if open.is_match(&line) {
+            errors.push(Error {
+                category: Category::FileDescriptor,
+                kind: "FdNotClosed".to_owned(),
+                what: line.into_owned(),
+                stack: vec![],
+                aux: vec![],
+            });
+            is_in_stack = true;
+        } else if line.contains("<inherited from parent>") {
+            // not opened by this program
+            errors.pop();
+            is_in_stack = false;
+        } else if let (true, Some(found), Some(error)) =
+            (is_in_stack, frame.captures(&line), errors.last_mut())
+        {
+            let (function, location) = (found[1].to_owned(), found[2].to_owned());
+            let line = found.get(3).and_then(|line| line.as_str().parse().ok());
+            error.stack.push(Frame {
+                function: Some(function),
+                file: line.map(|_| location.clone()),
+                line,
+                object: line.is_none().then_some(location),
+            });
+        } else {
+            is_in_stack = false;
+        }
+    }
+    errors
+}
+
+/// Reads the errors from a file written with --xml=yes, and the matching text log
+pub fn parse(xml: &str, log: &str) -> io::Result<Process> {
+    let document =
+        Document::parse(xml).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let root = document.root_element();
+    let id = |name| {
+        root.children()
+            .find(|child| child.has_tag_name(name))
+            .and_then(|child| child.text())
+            .and_then(|text| text.trim().parse().ok())
+            .unwrap_or_default()
+    };
+    let mut errors = root
+        .children()
+        .filter(|child| child.has_tag_name("error"))
+        .filter_map(Error::parse)
+        .collect::<Vec<_>>();
+    if errors
+        .iter()
+        .all(|error| error.category != Category::FileDescriptor)
+    {
+        errors.extend(fd_errors(log));
+    }
+    Ok(Process {
+        pid: id("pid"),
+        ppid: id("ppid"),
+        errors,
+    })
+}
@@ -318,6 +318,9 @@ impl UI {
             TestResult::None => "Test not run".to_string(),
             TestResult::Error(err) => err.clone(),
             TestResult::Failed(str) => str.clone(),
+            TestResult::Timeout(str) | TestResult::LimitExceeded(str) => str.clone(),
+            TestResult::MemoryError { output, .. } => output.clone(),
+            TestResult::Crashed { output, .. } => output.clone(),
             TestResult::Passed(str) => str.clone(),
         }
         .trim()